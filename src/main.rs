@@ -1,11 +1,34 @@
-use axum::{extract::DefaultBodyLimit, routing::post, Router};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        DefaultBodyLimit, Path, Query,
+    },
+    http::StatusCode,
+    response::{
+        sse::{Event, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post},
+    Json, Router,
+};
 use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
+    convert::Infallible,
+    os::unix::{
+        io::{AsRawFd, FromRawFd, RawFd},
+        process::{CommandExt, ExitStatusExt},
+    },
     process::Output,
-    sync::atomic::{AtomicUsize, Ordering},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     time::Duration,
 };
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{unix::AsyncFd, AsyncReadExt, AsyncWriteExt};
+use tokio_stream::wrappers::ReceiverStream;
 
 macro_rules! debug {
     ($($arg:tt)*) => {
@@ -26,8 +49,13 @@ async fn main() {
         .route("/py_exec", post(py_exec))
         .route("/any_exec", post(any_exec))
         .route("/py_coverage", post(coverage))
+        .route("/py_session", post(py_session_start))
+        .route("/py_session/:id", get(py_session_ws))
+        .route("/py_exec_stream", post(py_exec_stream))
         .layer(DefaultBodyLimit::max(std::usize::MAX));
 
+    spawn_session_sweeper();
+
     axum::Server::bind(&"0.0.0.0:8000".parse().unwrap())
         .serve(app.into_make_service())
         .await
@@ -45,6 +73,67 @@ lazy_static! {
         let cpus = *CPUS_AVAILABLE;
         mem / cpus
     };
+    static ref SESSION_IDX: AtomicUsize = AtomicUsize::new(0);
+    static ref SESSIONS: Mutex<HashMap<String, Arc<Mutex<PtySession>>>> =
+        Mutex::new(HashMap::new());
+    // how long a py_session may sit with no bytes flowing either way before it's reaped
+    static ref SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(
+        std::env::var("SESSION_IDLE_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(120)
+    );
+    // hard ceiling on a py_session's lifetime, regardless of activity
+    static ref SESSION_TOTAL_TIMEOUT: Duration = Duration::from_secs(
+        std::env::var("SESSION_TOTAL_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(600)
+    );
+    // total bytes /py_exec_stream will forward to the client before truncating,
+    // so a runaway print loop can't turn into an unbounded response
+    static ref STREAM_OUTPUT_LIMIT: usize = std::env::var("STREAM_OUTPUT_LIMIT_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(10 * 1024 * 1024);
+    // default RLIMIT_CPU, in seconds; independent of the async wall-clock timeout
+    static ref DEFAULT_CPU_LIMIT_SECS: u64 = std::env::var("CPU_LIMIT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(30);
+    // default RLIMIT_NPROC, to cap fork-bombs
+    static ref DEFAULT_NPROC_LIMIT: u64 = std::env::var("NPROC_LIMIT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(32);
+    // default RLIMIT_FSIZE, in bytes; same per-cpu budget as MEMORY_LIMIT
+    static ref DEFAULT_FSIZE_LIMIT_BYTES: u64 = std::env::var("FSIZE_LIMIT_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or((*MEMORY_LIMIT as u64) * 1024);
+    // default RLIMIT_NOFILE
+    static ref DEFAULT_NOFILE_LIMIT: u64 = std::env::var("NOFILE_LIMIT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(256);
+    // max warm workers kept alive across ALL exec kinds combined (plain
+    // python and every MultiPL-E language share this one budget, so fanning
+    // a workload out across languages can't oversubscribe the host beyond
+    // CPUS_AVAILABLE live interpreters)
+    static ref WORKER_POOL_SIZE: usize = std::env::var("WORKER_POOL_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(*CPUS_AVAILABLE);
+    // a worker is killed instead of reused after this many jobs, to bound
+    // any state/fd/memory drift across its lifetime
+    static ref WORKER_RECYCLE_AFTER: usize = std::env::var("WORKER_RECYCLE_AFTER_JOBS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(50);
+    static ref WORKER_IDLE: Mutex<HashMap<String, Vec<PoolWorker>>> = Mutex::new(HashMap::new());
+    // total live workers across every kind (idle + checked out); this is the
+    // single budget WORKER_POOL_SIZE caps
+    static ref WORKER_COUNT: Mutex<usize> = Mutex::new(0);
 }
 
 async fn create_temp_file(ext: &str) -> String {
@@ -80,11 +169,61 @@ impl From<std::string::FromUtf8Error> for ExecError {
 
 type ExecResult = Result<Output, ExecError>;
 
+/// Per-request sandbox limits. `None` fields fall back to the server-wide
+/// defaults; a present value may only tighten a default, never loosen it
+/// (see `resolve_limits`).
+#[derive(Deserialize, Clone, Copy, Default)]
+struct Limits {
+    #[serde(default)]
+    cpu_secs: Option<u64>,
+    #[serde(default)]
+    nproc: Option<u64>,
+    #[serde(default)]
+    fsize_bytes: Option<u64>,
+    #[serde(default)]
+    nofile: Option<u64>,
+}
+
+impl Limits {
+    /// True when every field is `None`, i.e. the request asked for no
+    /// tightening at all and the server-wide defaults apply untouched —
+    /// the only case a pooled worker (which only ever runs with the
+    /// defaults) can legally serve.
+    fn is_default(&self) -> bool {
+        self.cpu_secs.is_none()
+            && self.nproc.is_none()
+            && self.fsize_bytes.is_none()
+            && self.nofile.is_none()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ResolvedLimits {
+    cpu_secs: u64,
+    nproc: u64,
+    fsize_bytes: u64,
+    nofile: u64,
+}
+
+fn tighten(default: u64, requested: Option<u64>) -> u64 {
+    requested.map(|v| v.min(default)).unwrap_or(default)
+}
+
+fn resolve_limits(limits: &Limits) -> ResolvedLimits {
+    ResolvedLimits {
+        cpu_secs: tighten(*DEFAULT_CPU_LIMIT_SECS, limits.cpu_secs),
+        nproc: tighten(*DEFAULT_NPROC_LIMIT, limits.nproc),
+        fsize_bytes: tighten(*DEFAULT_FSIZE_LIMIT_BYTES, limits.fsize_bytes),
+        nofile: tighten(*DEFAULT_NOFILE_LIMIT, limits.nofile),
+    }
+}
+
 async fn run_program_with_timeout(
     program: &str,
     args: &[&str],
     stdin_data: &[u8],
     timeout: Duration,
+    limits: ResolvedLimits,
 ) -> ExecResult {
     let mut child = unsafe {
         tokio::process::Command::new(program)
@@ -107,6 +246,34 @@ async fn run_program_with_timeout(
                     (*MEMORY_LIMIT).try_into().unwrap(),
                     (*MEMORY_LIMIT).try_into().unwrap(),
                 )?;
+                // cap CPU seconds, independent of the async wall-clock timeout.
+                // the hard limit is kept one second above the soft limit so
+                // the kernel raises SIGXCPU (detectable via
+                // `killed_by_cpu_limit`) instead of going straight to a
+                // SIGKILL, which a soft==hard limit triggers immediately
+                nix::sys::resource::setrlimit(
+                    nix::sys::resource::Resource::RLIMIT_CPU,
+                    limits.cpu_secs,
+                    limits.cpu_secs.saturating_add(1),
+                )?;
+                // cap spawned processes, to blunt fork-bombs
+                nix::sys::resource::setrlimit(
+                    nix::sys::resource::Resource::RLIMIT_NPROC,
+                    limits.nproc,
+                    limits.nproc,
+                )?;
+                // cap the size of any file the child writes
+                nix::sys::resource::setrlimit(
+                    nix::sys::resource::Resource::RLIMIT_FSIZE,
+                    limits.fsize_bytes,
+                    limits.fsize_bytes,
+                )?;
+                // cap open file descriptors
+                nix::sys::resource::setrlimit(
+                    nix::sys::resource::Resource::RLIMIT_NOFILE,
+                    limits.nofile,
+                    limits.nofile,
+                )?;
                 Ok(())
             })
             .spawn()?
@@ -149,37 +316,805 @@ async fn run_program_with_timeout(
     }
 }
 
-fn out_to_res(output: ExecResult) -> String {
+/// Outcome of an exec, regardless of whether it ran as a fresh one-shot
+/// spawn (`run_program_with_timeout`) or was dispatched to a warm worker
+/// from the pool (see the `worker_pool` section below) — `py_exec` and
+/// `any_exec` don't need to care which path produced it.
+enum ExecOutput {
+    Exited {
+        exit_code: i32,
+        stdout: String,
+        stderr: String,
+        killed_by_cpu_limit: bool,
+        killed_by_fsize_limit: bool,
+    },
+    Timeout,
+    IoError(String),
+}
+
+fn exec_result_to_output(result: ExecResult) -> ExecOutput {
+    match result {
+        Ok(o) => ExecOutput::Exited {
+            exit_code: o.status.code().unwrap_or(-1),
+            stdout: String::from_utf8_lossy(&o.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&o.stderr).into_owned(),
+            killed_by_cpu_limit: o.status.signal() == Some(nix::libc::SIGXCPU),
+            killed_by_fsize_limit: o.status.signal() == Some(nix::libc::SIGXFSZ),
+        },
+        Err(ExecError::Timeout) => ExecOutput::Timeout,
+        Err(ExecError::IoError(e)) => ExecOutput::IoError(e.to_string()),
+        Err(ExecError::Utf8Error(e)) => ExecOutput::IoError(e.to_string()),
+    }
+}
+
+// kept around for `?legacy=true`, see ExecStatus/ExecRes for the typed replacement
+fn out_to_res(output: ExecOutput) -> String {
     match output {
-        Ok(o) if o.status.code().unwrap_or(-1) == 0 => {
-            format!("0\n{}", String::from_utf8_lossy(&o.stdout))
+        ExecOutput::Exited {
+            exit_code, stdout, ..
+        } if exit_code == 0 => format!("0\n{}", stdout),
+        ExecOutput::Exited { stderr, .. } => format!("1\n{}", stderr),
+        ExecOutput::Timeout => "1\nTimeout".to_string(),
+        ExecOutput::IoError(e) => format!("1\n{}", e),
+    }
+}
+
+/// Query flag so existing clients can opt back into the old `"0\n..."` /
+/// `"1\n..."` string protocol while new clients get typed JSON by default.
+#[derive(Deserialize)]
+struct LegacyQuery {
+    #[serde(default)]
+    legacy: bool,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum ExecStatus {
+    Ok,
+    NonZero,
+    Timeout,
+    IoError,
+    // child was killed by the kernel for exceeding RLIMIT_CPU / RLIMIT_FSIZE,
+    // distinguishable from a normal non-zero exit
+    CpuLimitExceeded,
+    FileSizeLimitExceeded,
+}
+
+#[derive(Serialize)]
+struct ExecRes {
+    status: ExecStatus,
+    exit_code: i32,
+    stdout: String,
+    stderr: String,
+}
+
+/// Typed replacement for `out_to_res`: keeps stdout and stderr distinct
+/// instead of merging them based on exit status, so callers can see stderr
+/// on a successful run and stdout on a failed one.
+fn out_to_exec_res(output: ExecOutput) -> ExecRes {
+    match output {
+        ExecOutput::Exited {
+            exit_code,
+            stdout,
+            stderr,
+            killed_by_cpu_limit,
+            killed_by_fsize_limit,
+        } => {
+            let status = if killed_by_cpu_limit {
+                ExecStatus::CpuLimitExceeded
+            } else if killed_by_fsize_limit {
+                ExecStatus::FileSizeLimitExceeded
+            } else if exit_code == 0 {
+                ExecStatus::Ok
+            } else {
+                ExecStatus::NonZero
+            };
+            ExecRes {
+                status,
+                exit_code,
+                stdout,
+                stderr,
+            }
         }
-        Ok(o) => format!("1\n{}", String::from_utf8_lossy(&o.stderr)),
-        Err(ExecError::Timeout) => "1\nTimeout".to_string(),
-        Err(ExecError::IoError(e)) => format!("1\n{}", e),
-        Err(ExecError::Utf8Error(e)) => format!("1\n{}", e),
+        ExecOutput::Timeout => ExecRes {
+            status: ExecStatus::Timeout,
+            exit_code: -1,
+            stdout: String::new(),
+            stderr: "Timeout".to_string(),
+        },
+        ExecOutput::IoError(e) => ExecRes {
+            status: ExecStatus::IoError,
+            exit_code: -1,
+            stdout: String::new(),
+            stderr: e,
+        },
     }
 }
 
-async fn run_py_code(code: &str, timeout: u64, stdin: String) -> String {
-    let output = run_program_with_timeout(
-        "python3",
-        &["-c", code],
-        stdin.as_bytes(),
-        Duration::from_secs(timeout),
+#[derive(Deserialize)]
+struct ExecReq {
+    code: String,
+    #[serde(default)]
+    timeout: u64,
+    #[serde(default)]
+    stdin: String,
+    #[serde(default)]
+    limits: Limits,
+}
+
+#[derive(Deserialize)]
+struct AnyExecReq {
+    code: String,
+    lang: String,
+    #[serde(default)]
+    timeout: u64,
+    #[serde(default)]
+    limits: Limits,
+}
+
+#[derive(Deserialize)]
+struct CoverageReq {
+    code: String,
+    #[serde(default)]
+    timeout: u64,
+    #[serde(default)]
+    limits: Limits,
+}
+
+/// Pumps a single stream (stdout or stderr) in fixed-size chunks, forwarding
+/// each chunk as an SSE event tagged with `event_name` and stopping once
+/// `remaining` (shared across both streams) hits zero.
+///
+/// A multi-byte UTF-8 character can straddle two 8 KiB reads, so each read
+/// is appended to a small `leftover` buffer and everything up to a trailing
+/// incomplete sequence is converted and sent (bytes that are invalid outright
+/// become replacement characters); that trailing partial sequence (at most 3
+/// bytes) carries over to the next read instead of being lossily converted
+/// on its own and corrupted into replacement characters.
+async fn pump_stream<R: AsyncReadExt + Unpin>(
+    mut reader: R,
+    event_name: &'static str,
+    tx: tokio::sync::mpsc::Sender<Result<Event, Infallible>>,
+    remaining: Arc<Mutex<usize>>,
+) {
+    let mut buf = [0u8; 8192];
+    let mut leftover: Vec<u8> = Vec::new();
+    loop {
+        let n = match reader.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => n,
+        };
+        let allowed = {
+            let mut remaining = remaining.lock().unwrap();
+            let take = n.min(*remaining);
+            *remaining -= take;
+            take
+        };
+        if allowed == 0 {
+            break;
+        }
+        leftover.extend_from_slice(&buf[..allowed]);
+        let mut valid_len = 0;
+        loop {
+            match std::str::from_utf8(&leftover[valid_len..]) {
+                Ok(_) => {
+                    valid_len = leftover.len();
+                    break;
+                }
+                Err(e) => match e.error_len() {
+                    Some(bad) => valid_len += e.valid_up_to() + bad,
+                    None => {
+                        valid_len += e.valid_up_to();
+                        break;
+                    }
+                },
+            }
+        }
+        let chunk = String::from_utf8_lossy(&leftover[..valid_len]).into_owned();
+        leftover.drain(..valid_len);
+        if chunk.is_empty() {
+            continue;
+        }
+        if tx
+            .send(Ok(Event::default().event(event_name).data(chunk)))
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+    if !leftover.is_empty() {
+        let chunk = String::from_utf8_lossy(&leftover).into_owned();
+        tx.send(Ok(Event::default().event(event_name).data(chunk)))
+            .await
+            .ok();
+    }
+}
+
+/// Streaming counterpart of `run_program_with_timeout`: forwards stdout and
+/// stderr to `tx` as they're produced instead of buffering the whole output,
+/// then sends a final `done` event carrying the exit code, or `Timeout`,
+/// `CpuLimitExceeded`, `FileSizeLimitExceeded`, or `IoError\n<error>`.
+async fn run_program_streaming(
+    program: &str,
+    args: &[&str],
+    stdin_data: &[u8],
+    timeout: Duration,
+    limits: ResolvedLimits,
+    tx: tokio::sync::mpsc::Sender<Result<Event, Infallible>>,
+) {
+    let mut child = unsafe {
+        match tokio::process::Command::new(program)
+            .args(args)
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .stdin(std::process::Stdio::piped())
+            .pre_exec(move || {
+                nix::unistd::setgid(nix::unistd::Gid::from_raw(1000))?;
+                nix::unistd::setuid(nix::unistd::Uid::from_raw(1000))?;
+                nix::sys::resource::setrlimit(
+                    nix::sys::resource::Resource::RLIMIT_AS,
+                    (*MEMORY_LIMIT).try_into().unwrap(),
+                    (*MEMORY_LIMIT).try_into().unwrap(),
+                )?;
+                // see run_program_with_timeout for why the hard limit sits
+                // one second above the soft limit
+                nix::sys::resource::setrlimit(
+                    nix::sys::resource::Resource::RLIMIT_CPU,
+                    limits.cpu_secs,
+                    limits.cpu_secs.saturating_add(1),
+                )?;
+                nix::sys::resource::setrlimit(
+                    nix::sys::resource::Resource::RLIMIT_NPROC,
+                    limits.nproc,
+                    limits.nproc,
+                )?;
+                nix::sys::resource::setrlimit(
+                    nix::sys::resource::Resource::RLIMIT_FSIZE,
+                    limits.fsize_bytes,
+                    limits.fsize_bytes,
+                )?;
+                nix::sys::resource::setrlimit(
+                    nix::sys::resource::Resource::RLIMIT_NOFILE,
+                    limits.nofile,
+                    limits.nofile,
+                )?;
+                Ok(())
+            })
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => {
+                tx.send(Ok(Event::default()
+                    .event("done")
+                    .data(format!("IoError\n{}", e))))
+                    .await
+                    .ok();
+                return;
+            }
+        }
+    };
+
+    let mut stdin = child.stdin.take().unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+    let remaining = Arc::new(Mutex::new(*STREAM_OUTPUT_LIMIT));
+
+    // writing stdin has to run concurrently with (and under the same
+    // timeout as) draining stdout/stderr: a program that echoes a large
+    // stdin will block writing stdout once its pipe fills, which blocks it
+    // reading more stdin, which would deadlock write_all if it ran to
+    // completion before the pumps started
+    let write_stdin = async {
+        if !stdin_data.is_empty() {
+            stdin.write_all(stdin_data).await.ok();
+        }
+        drop(stdin);
+    };
+
+    let pump = async {
+        tokio::join!(
+            write_stdin,
+            pump_stream(stdout, "stdout", tx.clone(), Arc::clone(&remaining)),
+            pump_stream(stderr, "stderr", tx.clone(), Arc::clone(&remaining)),
+        );
+        child.wait().await
+    };
+
+    match tokio::time::timeout(timeout, pump).await {
+        Ok(Ok(status)) => {
+            let exit_code = status.code().unwrap_or(-1);
+            let data = match status.signal() {
+                Some(nix::libc::SIGXCPU) => "CpuLimitExceeded".to_string(),
+                Some(nix::libc::SIGXFSZ) => "FileSizeLimitExceeded".to_string(),
+                _ => exit_code.to_string(),
+            };
+            tx.send(Ok(Event::default().event("done").data(data)))
+                .await
+                .ok();
+        }
+        Ok(Err(e)) => {
+            tx.send(Ok(Event::default()
+                .event("done")
+                .data(format!("IoError\n{}", e))))
+                .await
+                .ok();
+        }
+        Err(_) => {
+            child.kill().await.ok();
+            tx.send(Ok(Event::default().event("done").data("Timeout")))
+                .await
+                .ok();
+        }
+    }
+}
+
+async fn py_exec_stream(
+    Json(req): Json<ExecReq>,
+) -> Sse<ReceiverStream<Result<Event, Infallible>>> {
+    let (tx, rx) = tokio::sync::mpsc::channel(16);
+    let limits = resolve_limits(&req.limits);
+    tokio::spawn(async move {
+        let wrapped = format!("{}{}", RESET_SIGXFSZ_PREAMBLE, req.code);
+        run_program_streaming(
+            "python3",
+            &["-c", &wrapped],
+            req.stdin.as_bytes(),
+            Duration::from_secs(req.timeout),
+            limits,
+            tx,
+        )
+        .await;
+    });
+
+    Sse::new(ReceiverStream::new(rx))
+}
+
+// worker_pool: a small number of long-lived, pre-hardened python3 workers
+// per exec "kind" (plain python, or `multipl_e:<lang>` with eval_<lang>
+// already imported), so a short /py_exec or /any_exec call can skip paying
+// for interpreter start-up (and, for MultiPL-E, the eval module import) on
+// every request. Jobs are dispatched over a tiny length-prefixed JSON
+// framing on the worker's stdin/stdout; a worker is recycled (killed, not
+// reused) once it dies, desyncs, or serves `WORKER_RECYCLE_AFTER` jobs.
+//
+// RLIMIT_CPU can't be applied once at worker spawn the way the one-shot path
+// applies it, since it accumulates over the worker's whole lifetime rather
+// than per job; instead the driver script (see `worker_driver_script`) bumps
+// the soft limit before every job to (cpu time used so far) + the default
+// budget, so a runaway job is still CPU-killed without starving later jobs
+// on the same worker. A request that tightens any limit field skips the
+// pool entirely and falls back to a fresh one-shot spawn, since a pooled
+// worker only ever runs with the server-wide defaults.
+struct PoolWorker {
+    child: tokio::process::Child,
+    stdin: tokio::process::ChildStdin,
+    stdout: tokio::process::ChildStdout,
+    jobs_done: usize,
+}
+
+/// Python source for a warm worker: reads 4-byte-big-endian-length-prefixed
+/// JSON job messages from stdin, runs `dispatch` against each one, and
+/// writes a length-prefixed `{"exit_code", "stdout", "stderr",
+/// "killed_by_cpu_limit"}` JSON reply back.
+///
+/// Per-job stdout/stderr capture is done with `os.dup2` onto a fresh temp
+/// file, not a `sys.stdout`/`io.StringIO` swap: dispatched code is
+/// untrusted and routinely writes to the real fd 1/2 directly (`os.write`,
+/// or a subprocess it shells out to that inherits stdio — the normal way
+/// `eval_<lang>` runs a compiled program), which would otherwise bypass the
+/// Python-level swap entirely and corrupt the length-prefixed reply this
+/// same fd carries. The length-prefixed protocol itself is therefore moved
+/// off fd 1 onto a fd `os.dup`'d from it once at start-up (`_framing_fd`),
+/// so job replies are never sharing an fd with whatever the dispatched code
+/// is doing to "stdout".
+///
+/// `RLIMIT_CPU` can't be set once at worker spawn time the way the one-shot
+/// path sets it, since it accumulates over the whole process lifetime and
+/// would starve later jobs for a long-lived worker; instead the soft limit
+/// is bumped to (cpu time used so far) + `cpu_limit_secs` before every job,
+/// so each job gets its own budget while the worker keeps running.
+fn worker_driver_script(preamble: &str, dispatch: &str, cpu_limit_secs: u64) -> String {
+    format!(
+        r#"
+import sys, struct, io, json, traceback, signal, resource, os, tempfile
+
+# fd 1 is about to become fair game for dispatched code (directly, or via a
+# subprocess it spawns with inherited stdio), so the job-reply protocol gets
+# its own fd, duped from the original fd 1 before any job runs
+_framing_fd = os.dup(1)
+
+def _read_msg():
+    hdr = sys.stdin.buffer.read(4)
+    if len(hdr) < 4:
+        return None
+    (n,) = struct.unpack('>I', hdr)
+    return json.loads(sys.stdin.buffer.read(n).decode('utf-8'))
+
+def _write_msg(obj):
+    data = json.dumps(obj).encode('utf-8')
+    os.write(_framing_fd, struct.pack('>I', len(data)))
+    os.write(_framing_fd, data)
+
+class _CpuLimitExceeded(Exception):
+    pass
+
+def _on_sigxcpu(signum, frame):
+    raise _CpuLimitExceeded()
+
+signal.signal(signal.SIGXCPU, _on_sigxcpu)
+
+{preamble}
+
+while True:
+    _msg = _read_msg()
+    if _msg is None:
+        break
+    _out_f = tempfile.TemporaryFile(buffering=0)
+    _err_f = tempfile.TemporaryFile(buffering=0)
+    _saved_out_fd, _saved_err_fd = os.dup(1), os.dup(2)
+    os.dup2(_out_f.fileno(), 1)
+    os.dup2(_err_f.fileno(), 2)
+    _old_in = sys.stdin
+    sys.stdin = io.StringIO(_msg.get('stdin', ''))
+    _exit_code = 0
+    _killed_by_cpu_limit = False
+    _usage = resource.getrusage(resource.RUSAGE_SELF)
+    try:
+        resource.setrlimit(
+            resource.RLIMIT_CPU,
+            (int(_usage.ru_utime + _usage.ru_stime) + {cpu_limit_secs}, resource.RLIM_INFINITY),
+        )
+    except (ValueError, OSError):
+        pass
+    try:
+        {dispatch}
+    except _CpuLimitExceeded:
+        _exit_code = 1
+        _killed_by_cpu_limit = True
+    except SystemExit as e:
+        _exit_code = e.code if isinstance(e.code, int) else (0 if e.code is None else 1)
+    except Exception:
+        traceback.print_exc()
+        _exit_code = 1
+    finally:
+        sys.stdout.flush()
+        sys.stderr.flush()
+        os.dup2(_saved_out_fd, 1)
+        os.dup2(_saved_err_fd, 2)
+        os.close(_saved_out_fd)
+        os.close(_saved_err_fd)
+        sys.stdin = _old_in
+    _out_f.seek(0)
+    _err_f.seek(0)
+    _stdout_data = _out_f.read().decode('utf-8', 'replace')
+    _stderr_data = _err_f.read().decode('utf-8', 'replace')
+    _out_f.close()
+    _err_f.close()
+    _write_msg({{
+        "exit_code": _exit_code,
+        "stdout": _stdout_data,
+        "stderr": _stderr_data,
+        "killed_by_cpu_limit": _killed_by_cpu_limit,
+    }})
+"#,
+        preamble = preamble,
+        dispatch = dispatch,
+        cpu_limit_secs = cpu_limit_secs,
     )
-    .await;
+}
 
-    let res = out_to_res(output);
+fn worker_source_for_kind(kind: &str) -> String {
+    if let Some(lang) = kind.strip_prefix("multipl_e:") {
+        let preamble = format!(
+            "sys.path.append('{}/MultiPL-E/evaluation/src')\nimport eval_{}",
+            *CRATE_DIR, lang
+        );
+        let dispatch = format!(
+            "_result = eval_{}.eval_script(_msg['tempfile']); print(json.dumps(_result))",
+            lang
+        );
+        worker_driver_script(&preamble, &dispatch, *DEFAULT_CPU_LIMIT_SECS)
+    } else {
+        worker_driver_script(
+            "",
+            "exec(_msg['code'], {'__name__': '__main__'})",
+            *DEFAULT_CPU_LIMIT_SECS,
+        )
+    }
+}
 
-    debug!("{}", res);
-    res
+fn spawn_worker(kind: &str) -> std::io::Result<PoolWorker> {
+    let source = worker_source_for_kind(kind);
+    let limits = resolve_limits(&Limits::default());
+    let mut child = unsafe {
+        tokio::process::Command::new("python3")
+            .arg("-c")
+            .arg(&source)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::null())
+            .pre_exec(move || {
+                nix::unistd::setgid(nix::unistd::Gid::from_raw(1000))?;
+                nix::unistd::setuid(nix::unistd::Uid::from_raw(1000))?;
+                nix::sys::resource::setrlimit(
+                    nix::sys::resource::Resource::RLIMIT_AS,
+                    (*MEMORY_LIMIT).try_into().unwrap(),
+                    (*MEMORY_LIMIT).try_into().unwrap(),
+                )?;
+                nix::sys::resource::setrlimit(
+                    nix::sys::resource::Resource::RLIMIT_NPROC,
+                    limits.nproc,
+                    limits.nproc,
+                )?;
+                nix::sys::resource::setrlimit(
+                    nix::sys::resource::Resource::RLIMIT_FSIZE,
+                    limits.fsize_bytes,
+                    limits.fsize_bytes,
+                )?;
+                nix::sys::resource::setrlimit(
+                    nix::sys::resource::Resource::RLIMIT_NOFILE,
+                    limits.nofile,
+                    limits.nofile,
+                )?;
+                Ok(())
+            })
+            .spawn()?
+    };
+    let stdin = child.stdin.take().unwrap();
+    let stdout = child.stdout.take().unwrap();
+    Ok(PoolWorker {
+        child,
+        stdin,
+        stdout,
+        jobs_done: 0,
+    })
 }
 
-async fn run_multipl_e_prog(code: &str, lang: &str, timeout: u64) -> (String, String) {
+/// RAII handle around a checked-out `PoolWorker`. `try_pooled_exec` awaits
+/// `worker_roundtrip` while holding one of these; if the enclosing request
+/// future is dropped mid-await (e.g. the client disconnects), `Drop` kills
+/// the child and releases its slot in `WORKER_COUNT` instead of leaking it —
+/// without this, a cancelled request would leave the worker's slot
+/// permanently unaccounted for and eventually starve the whole pool. The
+/// success/failure paths call `checkin`, which takes the worker out of the
+/// guard so `Drop` sees `None` and does nothing.
+struct WorkerGuard {
+    kind: String,
+    worker: Option<PoolWorker>,
+}
+
+impl WorkerGuard {
+    fn get_mut(&mut self) -> &mut PoolWorker {
+        self.worker.as_mut().unwrap()
+    }
+
+    /// Hands the worker to `checkin_worker`, defusing the drop guard so the
+    /// pool count isn't touched a second time.
+    fn checkin(mut self, alive: bool) {
+        let worker = self.worker.take().unwrap();
+        checkin_worker(&self.kind, worker, alive);
+    }
+}
+
+impl Drop for WorkerGuard {
+    fn drop(&mut self) {
+        if let Some(mut worker) = self.worker.take() {
+            worker.child.start_kill().ok();
+            release_worker_slot();
+        }
+    }
+}
+
+/// Releases one slot from the shared `WORKER_COUNT` budget. `saturating_sub`
+/// guards against a double-release (e.g. a future bug in the
+/// cancel/checkin interplay) underflowing the counter — on a `usize` that
+/// would otherwise wrap to `usize::MAX` and wedge `checkout_worker` into
+/// refusing every pooled worker, for every kind, until a restart.
+fn release_worker_slot() {
+    let mut count = WORKER_COUNT.lock().unwrap();
+    *count = count.saturating_sub(1);
+}
+
+/// Kills one idle worker parked under a kind other than `kind`, to free its
+/// slot for a fresh spawn under `kind` without growing `WORKER_COUNT` beyond
+/// `WORKER_POOL_SIZE`. Returns `false` if no other kind currently has an
+/// idle worker to reclaim (the pool is fully in flight, not just imbalanced
+/// across kinds). Without this, a kind whose traffic has moved on keeps its
+/// idle workers forever — since they're only ever reclaimed by being
+/// checked out again — permanently denying that share of the shared budget
+/// to every other kind.
+fn evict_idle_worker(kind: &str) -> bool {
+    let mut idle_pools = WORKER_IDLE.lock().unwrap();
+    let victim_kind = idle_pools
+        .iter()
+        .find(|(k, workers)| k.as_str() != kind && !workers.is_empty())
+        .map(|(k, _)| k.clone());
+    let Some(victim_kind) = victim_kind else {
+        return false;
+    };
+    let mut worker = idle_pools.get_mut(&victim_kind).unwrap().pop().unwrap();
+    drop(idle_pools);
+    worker.child.start_kill().ok();
+    true
+}
+
+async fn checkout_worker(kind: &str) -> Option<WorkerGuard> {
+    if let Some(worker) = WORKER_IDLE
+        .lock()
+        .unwrap()
+        .get_mut(kind)
+        .and_then(|idle| idle.pop())
+    {
+        return Some(WorkerGuard {
+            kind: kind.to_string(),
+            worker: Some(worker),
+        });
+    }
+    let mut count = WORKER_COUNT.lock().unwrap();
+    if *count >= *WORKER_POOL_SIZE {
+        drop(count);
+        if !evict_idle_worker(kind) {
+            return None;
+        }
+    } else {
+        *count += 1;
+        drop(count);
+    }
+    match spawn_worker(kind) {
+        Ok(worker) => Some(WorkerGuard {
+            kind: kind.to_string(),
+            worker: Some(worker),
+        }),
+        Err(_) => {
+            release_worker_slot();
+            None
+        }
+    }
+}
+
+fn checkin_worker(kind: &str, mut worker: PoolWorker, alive: bool) {
+    if alive && worker.jobs_done < *WORKER_RECYCLE_AFTER {
+        WORKER_IDLE
+            .lock()
+            .unwrap()
+            .entry(kind.to_string())
+            .or_default()
+            .push(worker);
+        return;
+    }
+    worker.child.start_kill().ok();
+    release_worker_slot();
+}
+
+async fn worker_roundtrip(
+    worker: &mut PoolWorker,
+    payload: &serde_json::Value,
+) -> std::io::Result<(i32, String, String, bool)> {
+    let data = serde_json::to_vec(payload).unwrap();
+    worker
+        .stdin
+        .write_all(&(data.len() as u32).to_be_bytes())
+        .await?;
+    worker.stdin.write_all(&data).await?;
+    worker.stdin.flush().await?;
+
+    let mut len_buf = [0u8; 4];
+    worker.stdout.read_exact(&mut len_buf).await?;
+    let len = u32::from_be_bytes(len_buf) as usize;
+    let mut buf = vec![0u8; len];
+    worker.stdout.read_exact(&mut buf).await?;
+    let resp: serde_json::Value = serde_json::from_slice(&buf)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+    Ok((
+        resp.get("exit_code").and_then(|v| v.as_i64()).unwrap_or(-1) as i32,
+        resp.get("stdout")
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string(),
+        resp.get("stderr")
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string(),
+        resp.get("killed_by_cpu_limit")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false),
+    ))
+}
+
+/// Tries to service a job from the warm pool; returns `None` (never touching
+/// the pool's worker count) if the pool is exhausted so the caller can fall
+/// back to a one-shot spawn. A worker that dies, desyncs, or overruns
+/// `timeout` is dropped rather than returned to the pool.
+async fn try_pooled_exec(
+    kind: &str,
+    payload: serde_json::Value,
+    timeout: Duration,
+) -> Option<ExecOutput> {
+    let mut worker = checkout_worker(kind).await?;
+    match tokio::time::timeout(timeout, worker_roundtrip(worker.get_mut(), &payload)).await {
+        Ok(Ok((exit_code, stdout, stderr, killed_by_cpu_limit))) => {
+            worker.get_mut().jobs_done += 1;
+            worker.checkin(true);
+            Some(ExecOutput::Exited {
+                exit_code,
+                stdout,
+                stderr,
+                killed_by_cpu_limit,
+                killed_by_fsize_limit: false,
+            })
+        }
+        Ok(Err(_)) => {
+            worker.checkin(false);
+            None
+        }
+        Err(_) => {
+            worker.checkin(false);
+            Some(ExecOutput::Timeout)
+        }
+    }
+}
+
+// CPython sets SIGXFSZ's disposition to SIG_IGN at interpreter start-up (to
+// avoid being killed outright the first time a child writes a large file),
+// which means exceeding RLIMIT_FSIZE normally surfaces as a plain
+// `OSError: [Errno 27]` from `write()` rather than a signal — so
+// `killed_by_fsize_limit` could never fire for a `python3` child. The
+// one-shot `python3 -c` spawns (`py_exec_stream`, and the non-pooled paths of
+// `run_py_code`/`run_multipl_e_prog`) prepend this, so resetting the
+// disposition back to default before the caller's code runs restores the
+// same kernel-level signal detection `exec_result_to_output`/`run_program_streaming`
+// already look for. Pooled workers don't run it: a pooled job that exceeds
+// RLIMIT_FSIZE gets the `OSError` and is reported as an ordinary non-zero
+// exit (`try_pooled_exec` always sets `killed_by_fsize_limit: false`).
+// `coverage` runs the `coverage` executable without it either.
+const RESET_SIGXFSZ_PREAMBLE: &str =
+    "import signal as _signal; _signal.signal(_signal.SIGXFSZ, _signal.SIG_DFL)\n";
+
+async fn run_py_code(code: &str, timeout: u64, stdin: &str, limits: &Limits) -> ExecOutput {
+    if limits.is_default() {
+        let payload = serde_json::json!({ "code": code, "stdin": stdin });
+        if let Some(output) = try_pooled_exec("py", payload, Duration::from_secs(timeout)).await {
+            return output;
+        }
+    }
+    let wrapped = format!("{}{}", RESET_SIGXFSZ_PREAMBLE, code);
+    exec_result_to_output(
+        run_program_with_timeout(
+            "python3",
+            &["-c", &wrapped],
+            stdin.as_bytes(),
+            Duration::from_secs(timeout),
+            resolve_limits(limits),
+        )
+        .await,
+    )
+}
+
+async fn run_multipl_e_prog(
+    code: &str,
+    lang: &str,
+    timeout: u64,
+    limits: &Limits,
+) -> (ExecOutput, String) {
     let tempfile = create_temp_file(lang).await;
     tokio::fs::write(&tempfile, code).await.unwrap();
 
+    if limits.is_default() {
+        let payload = serde_json::json!({ "tempfile": tempfile });
+        if let Some(output) = try_pooled_exec(
+            &format!("multipl_e:{}", lang),
+            payload,
+            Duration::from_secs(timeout),
+        )
+        .await
+        {
+            debug!("{}: pooled", tempfile);
+            return (output, tempfile);
+        }
+    }
+
     // method:
     // cwd into $CRATE_DIR/MultiPL-E/evaluation/src
     // run `python3 -c "import eval_$lang; eval_$lang.eval_script('$tempfile')"`
@@ -188,20 +1123,25 @@ async fn run_multipl_e_prog(code: &str, lang: &str, timeout: u64) -> (String, St
         &[
             "-c",
             &format!(
-                "import sys; sys.path.append('{}/MultiPL-E/evaluation/src'); import json; import eval_{}; print(json.dumps(eval_{}.eval_script('{}')))",
-                *CRATE_DIR, lang, lang, tempfile
+                "{}import sys; sys.path.append('{}/MultiPL-E/evaluation/src'); import json; import eval_{}; print(json.dumps(eval_{}.eval_script('{}')))",
+                RESET_SIGXFSZ_PREAMBLE, *CRATE_DIR, lang, lang, tempfile
             ),
         ],
         &[], // TODO: add stdin opt for multipl-e
         Duration::from_secs(timeout),
+        resolve_limits(limits),
     ).await;
-    let res = out_to_res(output);
 
-    debug!("{}: {}", tempfile, res);
-    (res, tempfile)
+    debug!(
+        "{}: {:?}",
+        tempfile,
+        output.as_ref().map(|o| o.status.code())
+    );
+    (exec_result_to_output(output), tempfile)
 }
 
-/// hacky but i'm lazy
+/// hacky but i'm lazy — still used by py_session_start, which only needs
+/// the one `lang` field and isn't worth a dedicated request struct for.
 fn get_string_json(json: &str, key: &str) -> String {
     serde_json::from_str::<serde_json::Value>(json)
         .map(|v| {
@@ -214,84 +1154,488 @@ fn get_string_json(json: &str, key: &str) -> String {
         .unwrap_or_default()
 }
 
-fn get_int_json(json: &str, key: &str) -> i64 {
-    serde_json::from_str::<serde_json::Value>(json)
-        .map(|v| {
-            v.get(key)
-                .unwrap_or(&serde_json::Value::Null)
-                .as_i64()
-                .unwrap_or(0)
-        })
-        .unwrap_or(0)
+#[derive(Serialize)]
+struct CoverageRes {
+    status: ExecStatus,
+    exit_code: i32,
+    stdout: String,
+    stderr: String,
+    coverage_percent: Option<f64>,
+}
+
+/// Parses the `NN%` cover column of the first row after the dashed header
+/// separator in `coverage report`'s stdout (the single measured file's row).
+fn parse_coverage_percent(stdout: &str) -> Option<f64> {
+    let mut next_is_cov = false;
+    for line in stdout.lines() {
+        if next_is_cov {
+            let spacesplit = line
+                .split(' ')
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.trim_end_matches('%'))
+                .collect::<Vec<_>>();
+            return spacesplit.get(3)?.parse().ok();
+        } else if line.starts_with("---------") {
+            next_is_cov = true;
+        }
+    }
+    None
 }
 
-async fn coverage(json: String) -> String {
-    let code = get_string_json(&json, "code");
-    let timeout: u64 = get_int_json(&json, "timeout") as u64;
+async fn coverage(Query(q): Query<LegacyQuery>, Json(req): Json<CoverageReq>) -> Response {
     let tempfile = create_temp_file("py").await;
-    tokio::fs::write(&tempfile, code).await.unwrap();
+    tokio::fs::write(&tempfile, &req.code).await.unwrap();
     let cov_file = format!("{}.cov", tempfile);
-    let thunk = async {
-        let output = run_program_with_timeout(
-            "coverage",
-            &["run", "--data-file", cov_file.as_str(), tempfile.as_str()],
-            &[], // no stdin
-            Duration::from_secs(timeout),
-        )
-        .await
-        .ok()?;
-        if output.status.code()? != 0 {
-            return None;
-        }
-        let output = run_program_with_timeout(
-            "coverage",
-            &["report", "--data-file", cov_file.as_str()],
-            &[], // no stdin
-            Duration::from_secs(10),
-        )
-        .await
-        .ok()?;
-        if output.status.code()? != 0 {
-            return None;
-        }
-        let stdout = String::from_utf8(output.stdout).ok()?;
-        let mut cov_percentage: u8 = 0;
-        let mut next_is_cov = false;
-        for line in stdout.lines() {
-            if next_is_cov {
-                let spacesplit = line
-                    .split(' ')
-                    .map(|s| s.trim())
-                    .filter(|s| !s.is_empty())
-                    .map(|s| s.trim_end_matches('%'))
-                    .collect::<Vec<_>>();
-                cov_percentage = spacesplit.get(3)?.parse().ok()?;
-                break;
-            } else if line.starts_with("---------") {
-                next_is_cov = true;
+
+    let resolved = resolve_limits(&req.limits);
+    let run_output = run_program_with_timeout(
+        "coverage",
+        &["run", "--data-file", cov_file.as_str(), tempfile.as_str()],
+        &[], // no stdin
+        Duration::from_secs(req.timeout),
+        resolved,
+    )
+    .await;
+
+    let res = match run_output {
+        Ok(o) if o.status.code().unwrap_or(-1) == 0 => {
+            let report_output = run_program_with_timeout(
+                "coverage",
+                &["report", "--data-file", cov_file.as_str()],
+                &[], // no stdin
+                Duration::from_secs(10),
+                resolved,
+            )
+            .await;
+            match report_output {
+                Ok(report) => {
+                    let exit_code = report.status.code().unwrap_or(-1);
+                    let stdout = String::from_utf8_lossy(&report.stdout).into_owned();
+                    let coverage_percent = if exit_code == 0 {
+                        parse_coverage_percent(&stdout)
+                    } else {
+                        None
+                    };
+                    CoverageRes {
+                        status: if exit_code == 0 {
+                            ExecStatus::Ok
+                        } else {
+                            ExecStatus::NonZero
+                        },
+                        exit_code,
+                        stdout,
+                        stderr: String::from_utf8_lossy(&report.stderr).into_owned(),
+                        coverage_percent,
+                    }
+                }
+                Err(ExecError::Timeout) => CoverageRes {
+                    status: ExecStatus::Timeout,
+                    exit_code: -1,
+                    stdout: String::new(),
+                    stderr: "Timeout".to_string(),
+                    coverage_percent: None,
+                },
+                Err(e) => CoverageRes {
+                    status: ExecStatus::IoError,
+                    exit_code: -1,
+                    stdout: String::new(),
+                    stderr: format!("{:?}", e),
+                    coverage_percent: None,
+                },
             }
         }
-
-        Some(cov_percentage.to_string())
+        Ok(o) => CoverageRes {
+            status: ExecStatus::NonZero,
+            exit_code: o.status.code().unwrap_or(-1),
+            stdout: String::from_utf8_lossy(&o.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&o.stderr).into_owned(),
+            coverage_percent: None,
+        },
+        Err(ExecError::Timeout) => CoverageRes {
+            status: ExecStatus::Timeout,
+            exit_code: -1,
+            stdout: String::new(),
+            stderr: "Timeout".to_string(),
+            coverage_percent: None,
+        },
+        Err(e) => CoverageRes {
+            status: ExecStatus::IoError,
+            exit_code: -1,
+            stdout: String::new(),
+            stderr: format!("{:?}", e),
+            coverage_percent: None,
+        },
     };
-    let res = thunk.await.unwrap_or("-1".to_string());
+
     tokio::fs::remove_file(&tempfile).await.unwrap();
     tokio::fs::remove_file(&cov_file).await.ok(); // the file may not exist
-    res
+
+    if q.legacy {
+        match res.coverage_percent {
+            Some(p) => (p as i64).to_string(),
+            None => "-1".to_string(),
+        }
+        .into_response()
+    } else {
+        Json(res).into_response()
+    }
 }
 
-async fn py_exec(json: String) -> String {
-    let code = get_string_json(&json, "code");
-    let timeout: u64 = get_int_json(&json, "timeout") as u64;
-    let stdin = get_string_json(&json, "stdin");
-    run_py_code(&code, timeout, stdin).await
+async fn py_exec(Query(q): Query<LegacyQuery>, Json(req): Json<ExecReq>) -> Response {
+    let output = run_py_code(&req.code, req.timeout, &req.stdin, &req.limits).await;
+    if q.legacy {
+        out_to_res(output).into_response()
+    } else {
+        Json(out_to_exec_res(output)).into_response()
+    }
 }
 
-async fn any_exec(json: String) -> String {
-    let code = get_string_json(&json, "code");
-    let lang = get_string_json(&json, "lang");
-    let timeout: u64 = get_int_json(&json, "timeout") as u64;
-    let (res, tempfile) = run_multipl_e_prog(&code, &lang, timeout).await;
+async fn any_exec(Query(q): Query<LegacyQuery>, Json(req): Json<AnyExecReq>) -> Response {
+    let (output, tempfile) =
+        run_multipl_e_prog(&req.code, &req.lang, req.timeout, &req.limits).await;
     tokio::fs::remove_file(&tempfile).await.unwrap();
-    res
+    if q.legacy {
+        out_to_res(output).into_response()
+    } else {
+        Json(out_to_exec_res(output)).into_response()
+    }
+}
+
+// py_session: PTY-backed interactive sessions, for programs that need a real
+// controlling terminal (input(), pdb, `python3 -i`, ...) instead of the
+// plain piped stdin/stdout that `run_program_with_timeout` hands everything
+// else. A session is a child process whose stdio is the slave half of a
+// pty; the server keeps the master fd and shuttles bytes to/from it over a
+// WebSocket, so the caller can send multiple turns of stdin across separate
+// messages instead of the one-shot `stdin` field `py_exec` uses.
+struct PtySession {
+    master_fd: RawFd,
+    child: std::process::Child,
+    created_at: std::time::Instant,
+    last_active: std::time::Instant,
+    // set while a WebSocket is attached, so a second concurrent connect to
+    // the same session id can be rejected instead of racing the first
+    // socket's reads/writes against the same master_fd
+    attached: bool,
+}
+
+// lets us hand master_fd to AsyncFd without it trying to own/close the fd
+// itself; PtySession's Drop impl remains the sole owner.
+struct BorrowedRawFd(RawFd);
+
+impl AsRawFd for BorrowedRawFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+impl Drop for PtySession {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+        unsafe {
+            nix::libc::close(self.master_fd);
+        }
+    }
+}
+
+// handle_py_session_ws enforces SESSION_IDLE_TIMEOUT/SESSION_TOTAL_TIMEOUT,
+// but only for sessions that actually have a WebSocket attached — a session
+// started via py_session_start and never connected to would otherwise leak
+// its pty and child forever. This background task reaps those too.
+fn spawn_session_sweeper() {
+    tokio::spawn(async {
+        loop {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            let now = std::time::Instant::now();
+            let expired: Vec<String> = SESSIONS
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(_, session)| {
+                    let session = session.lock().unwrap();
+                    now.duration_since(session.last_active) > *SESSION_IDLE_TIMEOUT
+                        || now.duration_since(session.created_at) > *SESSION_TOTAL_TIMEOUT
+                })
+                .map(|(id, _)| id.clone())
+                .collect();
+            let mut sessions = SESSIONS.lock().unwrap();
+            for id in expired {
+                sessions.remove(&id);
+            }
+        }
+    });
+}
+
+#[derive(Serialize)]
+struct PySessionStartRes {
+    session_id: String,
+}
+
+#[derive(Serialize)]
+struct PySessionStartErr {
+    error: String,
+}
+
+/// Spawns `program` with its stdin/stdout/stderr attached to the slave side
+/// of a fresh pty, mirroring the setgid/setuid/rlimit hardening that
+/// `run_program_with_timeout` applies, and registers it in `SESSIONS`.
+fn spawn_pty_session(program: &str, args: &[&str]) -> Result<String, ExecError> {
+    let pty = nix::pty::openpty(None, None)
+        .map_err(|e| ExecError::IoError(std::io::Error::new(std::io::ErrorKind::Other, e)))?;
+    let master_fd = pty.master;
+    let slave_fd = pty.slave;
+
+    // handle_py_session_ws drives this fd with AsyncFd, which requires
+    // non-blocking reads/writes
+    nix::fcntl::fcntl(
+        master_fd,
+        nix::fcntl::FcntlArg::F_SETFL(nix::fcntl::OFlag::O_NONBLOCK),
+    )
+    .map_err(|e| ExecError::IoError(std::io::Error::new(std::io::ErrorKind::Other, e)))?;
+
+    // each Stdio below takes ownership of the fd it's built from and closes
+    // it on drop (they're dropped in the parent along with `cmd`, after
+    // spawn() has dup2'd them into the child), so stdout/stderr need their own dup of the slave
+    // rather than reusing the same fd value three times
+    let stdout_fd = nix::unistd::dup(slave_fd)
+        .map_err(|e| ExecError::IoError(std::io::Error::new(std::io::ErrorKind::Other, e)))?;
+    let stderr_fd = nix::unistd::dup(slave_fd)
+        .map_err(|e| ExecError::IoError(std::io::Error::new(std::io::ErrorKind::Other, e)))?;
+
+    let limits = resolve_limits(&Limits::default());
+    let mut cmd = std::process::Command::new(program);
+    cmd.args(args);
+    cmd.stdin(unsafe { std::process::Stdio::from_raw_fd(slave_fd) });
+    cmd.stdout(unsafe { std::process::Stdio::from_raw_fd(stdout_fd) });
+    cmd.stderr(unsafe { std::process::Stdio::from_raw_fd(stderr_fd) });
+    unsafe {
+        cmd.pre_exec(move || {
+            // restrict gid and uid, same as run_program_with_timeout
+            nix::unistd::setgid(nix::unistd::Gid::from_raw(1000))?;
+            nix::unistd::setuid(nix::unistd::Uid::from_raw(1000))?;
+            // limit memory
+            nix::sys::resource::setrlimit(
+                nix::sys::resource::Resource::RLIMIT_AS,
+                (*MEMORY_LIMIT).try_into().unwrap(),
+                (*MEMORY_LIMIT).try_into().unwrap(),
+            )?;
+            nix::sys::resource::setrlimit(
+                nix::sys::resource::Resource::RLIMIT_NPROC,
+                limits.nproc,
+                limits.nproc,
+            )?;
+            nix::sys::resource::setrlimit(
+                nix::sys::resource::Resource::RLIMIT_FSIZE,
+                limits.fsize_bytes,
+                limits.fsize_bytes,
+            )?;
+            nix::sys::resource::setrlimit(
+                nix::sys::resource::Resource::RLIMIT_NOFILE,
+                limits.nofile,
+                limits.nofile,
+            )?;
+            // NOTE: no RLIMIT_CPU here — an interactive session is expected
+            // to sit idle between turns of input, unlike a one-shot exec
+            // make the slave our controlling terminal so input()-style
+            // line discipline and isatty() checks behave
+            nix::unistd::setsid()?;
+            if nix::libc::ioctl(0, nix::libc::TIOCSCTTY as _, 0) < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+
+    // spawn() dup2's slave_fd/stdout_fd/stderr_fd into the child; the Stdio
+    // handles holding them are dropped with `cmd` when this function returns,
+    // which closes all three in the parent for us — no separate close(slave_fd) is needed (or correct: slave_fd is
+    // the same value as stdin's Stdio, so closing it again here would race
+    // with a meanwhile-recycled, unrelated fd).
+    let child = cmd.spawn()?;
+
+    let now = std::time::Instant::now();
+    let session_id = format!("{}", SESSION_IDX.fetch_add(1, Ordering::SeqCst));
+    let session = PtySession {
+        master_fd,
+        child,
+        created_at: now,
+        last_active: now,
+        attached: false,
+    };
+    SESSIONS
+        .lock()
+        .unwrap()
+        .insert(session_id.clone(), Arc::new(Mutex::new(session)));
+    Ok(session_id)
+}
+
+async fn py_session_start(json: String) -> Response {
+    let lang = get_string_json(&json, "lang");
+    let program = if lang.is_empty() || lang == "py" {
+        "python3".to_string()
+    } else {
+        lang
+    };
+    // reachable on ordinary input (e.g. `lang` not a real executable on
+    // PATH) as well as host pressure (openpty/setrlimit failing under
+    // load), so this has to be a normal error response, not a panic
+    match tokio::task::spawn_blocking(move || spawn_pty_session(&program, &["-i"]))
+        .await
+        .unwrap()
+    {
+        Ok(session_id) => Json(PySessionStartRes { session_id }).into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(PySessionStartErr {
+                error: format!("{:?}", e),
+            }),
+        )
+            .into_response(),
+    }
+}
+
+async fn py_session_ws(Path(id): Path<String>, ws: WebSocketUpgrade) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| handle_py_session_ws(id, socket))
+}
+
+/// Writes all of `data` to the pty master, retrying on `EAGAIN` (the fd is
+/// `O_NONBLOCK`, so a full pty input buffer makes a bare `write(2)` return
+/// `-1`/`EAGAIN`) and on short writes, instead of dropping whatever a single
+/// fire-and-forget `write(2)` didn't accept.
+async fn write_all_to_pty(master: &AsyncFd<BorrowedRawFd>, mut data: &[u8]) -> std::io::Result<()> {
+    while !data.is_empty() {
+        let mut guard = master.writable().await?;
+        let result = guard.try_io(|inner| {
+            let n = unsafe {
+                nix::libc::write(inner.as_raw_fd(), data.as_ptr() as *const _, data.len())
+            };
+            if n < 0 {
+                Err(std::io::Error::last_os_error())
+            } else {
+                Ok(n as usize)
+            }
+        });
+        match result {
+            Ok(Ok(n)) => data = &data[n..],
+            Ok(Err(e)) => return Err(e),
+            Err(_would_block) => {} // spurious readiness, loop and re-poll
+        }
+    }
+    Ok(())
+}
+
+async fn handle_py_session_ws(id: String, mut socket: WebSocket) {
+    // bound to its own statement rather than matched on directly: matching
+    // on the lock expression keeps the MutexGuard alive as a temporary for
+    // the whole match, including the None arm's `.await`, which makes this
+    // function's future `!Send` and breaks `ws.on_upgrade`
+    let found = SESSIONS.lock().unwrap().get(&id).cloned();
+    let session = match found {
+        Some(s) => s,
+        None => {
+            socket
+                .send(Message::Text("no such session".to_string()))
+                .await
+                .ok();
+            return;
+        }
+    };
+    let master_fd = {
+        let mut guard = session.lock().unwrap();
+        if guard.attached {
+            drop(guard);
+            socket
+                .send(Message::Text("session already has a client attached".to_string()))
+                .await
+                .ok();
+            return;
+        }
+        guard.attached = true;
+        guard.master_fd
+    };
+    // clears `attached` on every exit path (normal break, an early return
+    // below, or a panic unwinding out of this task) so a dead/misbehaving
+    // attach can't wedge the session as permanently "in use" until the
+    // sweeper eventually reaps it
+    struct AttachedGuard(Arc<Mutex<PtySession>>);
+    impl Drop for AttachedGuard {
+        fn drop(&mut self) {
+            self.0.lock().unwrap().attached = false;
+        }
+    }
+    let _attached_guard = AttachedGuard(session.clone());
+    // a single persistent, cancel-safe reader: re-spawning a spawn_blocking
+    // read every loop iteration detaches the in-flight read whenever the
+    // socket.recv() arm wins, leaking a blocking thread and racing the next
+    // iteration's read against it
+    let async_master = AsyncFd::new(BorrowedRawFd(master_fd))
+        .expect("failed to register pty master fd with the reactor");
+
+    loop {
+        tokio::select! {
+            guard = async_master.readable() => {
+                let mut guard = guard.expect("failed to poll pty master fd");
+                let result = guard.try_io(|inner| {
+                    let mut buf = [0u8; 4096];
+                    let n = unsafe {
+                        nix::libc::read(inner.as_raw_fd(), buf.as_mut_ptr() as *mut _, buf.len())
+                    };
+                    if n < 0 {
+                        Err(std::io::Error::last_os_error())
+                    } else {
+                        Ok((n as usize, buf))
+                    }
+                });
+                match result {
+                    Ok(Ok((0, _))) => break, // child exited or pty closed
+                    Ok(Ok((n, buf))) => {
+                        session.lock().unwrap().last_active = std::time::Instant::now();
+                        if socket.send(Message::Binary(buf[..n].to_vec())).await.is_err() {
+                            break;
+                        }
+                    }
+                    Ok(Err(_)) => break, // real read error
+                    Err(_would_block) => {} // spurious readiness, loop and re-poll
+                }
+            }
+            msg = socket.recv() => {
+                match msg {
+                    Some(Ok(Message::Binary(data))) => {
+                        session.lock().unwrap().last_active = std::time::Instant::now();
+                        if write_all_to_pty(&async_master, &data).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(Message::Text(text))) => {
+                        session.lock().unwrap().last_active = std::time::Instant::now();
+                        if write_all_to_pty(&async_master, text.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Err(_)) => break,
+                    _ => {}
+                }
+            }
+            // without this arm, select! blocks forever on a genuinely idle
+            // session (no pty output, no client message) and the timeout
+            // check below never runs
+            _ = tokio::time::sleep(*SESSION_IDLE_TIMEOUT) => {}
+        }
+
+        let (created_at, last_active) = {
+            let session = session.lock().unwrap();
+            (session.created_at, session.last_active)
+        };
+        let now = std::time::Instant::now();
+        if now.duration_since(last_active) > *SESSION_IDLE_TIMEOUT
+            || now.duration_since(created_at) > *SESSION_TOTAL_TIMEOUT
+        {
+            break;
+        }
+    }
+
+    SESSIONS.lock().unwrap().remove(&id);
 }